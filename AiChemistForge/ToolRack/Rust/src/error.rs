@@ -0,0 +1,20 @@
+use std::io;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ServiceError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Access denied: {0}")]
+    AccessDenied(String),
+
+    #[error("Invalid path: {0}")]
+    InvalidPath(String),
+
+    #[error("Index error: {0}")]
+    Index(String),
+}
+
+pub type ServiceResult<T> = Result<T, ServiceError>;
@@ -0,0 +1,360 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use serde_json::json;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::error::{ServiceError, ServiceResult};
+use crate::tree_index::TreeIndex;
+
+/// Digests are cached by the file's path together with the size/mtime pair that was
+/// hashed, so a change to the file invalidates its entry without needing an explicit flush.
+type DigestCacheKey = (PathBuf, u64, SystemTime);
+
+const HASH_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Central gatekeeper for all filesystem-touching tools.
+///
+/// Every tool routes its filesystem access through here so that the
+/// allowed-directory restriction is enforced in exactly one place.
+#[derive(Clone)]
+pub struct FileSystemService {
+    allowed_directories: Vec<PathBuf>,
+    digest_cache: Arc<Mutex<HashMap<DigestCacheKey, String>>>,
+    index: Option<Arc<TreeIndex>>,
+}
+
+impl FileSystemService {
+    pub fn new(allowed_directories: Vec<PathBuf>) -> Self {
+        Self {
+            allowed_directories,
+            digest_cache: Arc::new(Mutex::new(HashMap::new())),
+            index: None,
+        }
+    }
+
+    /// Enables the on-disk tree index, opening (or creating) a `sled` database at `index_path`.
+    pub fn with_index(mut self, index_path: &Path) -> ServiceResult<Self> {
+        self.index = Some(Arc::new(TreeIndex::open(index_path)?));
+        Ok(self)
+    }
+
+    pub fn index(&self) -> Option<&TreeIndex> {
+        self.index.as_deref()
+    }
+
+    pub fn is_path_allowed(&self, path: &Path) -> bool {
+        self.allowed_directories
+            .iter()
+            .any(|allowed| path.starts_with(allowed))
+    }
+
+    pub fn validate_path(&self, path: &Path) -> ServiceResult<PathBuf> {
+        if !self.is_path_allowed(path) {
+            return Err(ServiceError::AccessDenied(format!(
+                "{} is outside the allowed directories",
+                path.display()
+            )));
+        }
+        Ok(path.to_path_buf())
+    }
+
+    pub async fn list_directory(&self, path: &Path) -> ServiceResult<Vec<std::fs::DirEntry>> {
+        let path = self.validate_path(path)?;
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(&path)? {
+            entries.push(entry?);
+        }
+        Ok(entries)
+    }
+
+    /// `lstat`-style metadata for a single entry, used to enrich `directory_tree` output.
+    /// Does not follow symlinks, so callers can tell a link from its target.
+    pub fn entry_metadata(&self, path: &Path) -> ServiceResult<serde_json::Value> {
+        let metadata = std::fs::symlink_metadata(path)?;
+        let mut fields = serde_json::Map::new();
+
+        fields.insert("size".into(), json!(metadata.len()));
+        fields.insert("is_symlink".into(), json!(metadata.file_type().is_symlink()));
+        if let Ok(modified) = metadata.modified() {
+            fields.insert("modified".into(), json!(Self::system_time_to_rfc3339(modified)));
+        }
+        if let Ok(created) = metadata.created() {
+            fields.insert("created".into(), json!(Self::system_time_to_rfc3339(created)));
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            fields.insert("dev".into(), json!(metadata.dev()));
+            fields.insert("inode".into(), json!(metadata.ino()));
+            fields.insert("nlink".into(), json!(metadata.nlink()));
+            fields.insert("mode".into(), json!(metadata.mode()));
+        }
+
+        Ok(serde_json::Value::Object(fields))
+    }
+
+    fn system_time_to_rfc3339(time: SystemTime) -> String {
+        let datetime: chrono::DateTime<chrono::Utc> = time.into();
+        datetime.to_rfc3339()
+    }
+
+    /// Recursively collects every file path under `path`, following the same allowed-directory
+    /// restriction as `list_directory`. Used by tools that need a flat file list rather than a
+    /// nested tree (e.g. `find_duplicates`).
+    pub async fn walk_files(&self, path: &Path) -> ServiceResult<Vec<PathBuf>> {
+        let mut visited = HashSet::new();
+        self.walk_files_inner(path, &mut visited).await
+    }
+
+    /// Guards against symlink cycles the same way `DirectoryTreeTool::build_node` does: once a
+    /// canonical directory path has been visited, it isn't descended into again. Also enforces
+    /// the symlink-escape jail unconditionally (the same check `DirectoryTreeTool` now always
+    /// runs): a symlinked directory or file whose resolved target escapes every allowed root is
+    /// skipped rather than descended into or returned for hashing.
+    fn walk_files_inner<'a>(
+        &'a self,
+        path: &'a Path,
+        visited: &'a mut HashSet<PathBuf>,
+    ) -> Pin<Box<dyn Future<Output = ServiceResult<Vec<PathBuf>>> + 'a>> {
+        Box::pin(async move {
+            let real_path = std::fs::canonicalize(path).ok();
+            let allowed = real_path
+                .as_ref()
+                .is_some_and(|real| self.is_canonical_path_allowed(real));
+            if !allowed {
+                return Ok(Vec::new());
+            }
+
+            let canonical = real_path.unwrap_or_else(|| path.to_path_buf());
+            if !visited.insert(canonical) {
+                return Ok(Vec::new());
+            }
+
+            let mut files = Vec::new();
+            for entry in self.list_directory(path).await? {
+                let entry_path = entry.path();
+                if entry_path.is_dir() {
+                    files.extend(self.walk_files_inner(&entry_path, visited).await?);
+                } else {
+                    let entry_real = std::fs::canonicalize(&entry_path).ok();
+                    let entry_allowed = entry_real
+                        .as_ref()
+                        .is_some_and(|real| self.is_canonical_path_allowed(real));
+                    if entry_allowed {
+                        files.push(entry_path);
+                    }
+                }
+            }
+            Ok(files)
+        })
+    }
+
+    /// BLAKE3 digest of a file's contents, formatted as `"b3:<hex>"`. Streams the file in fixed
+    /// size chunks to bound memory, returns `Ok(None)` for files above `max_hash_bytes`, and
+    /// caches results by `(path, size, mtime)` so rescanning an unchanged file is a cache hit.
+    pub fn file_digest(&self, path: &Path, max_hash_bytes: Option<u64>) -> ServiceResult<Option<String>> {
+        let metadata = std::fs::metadata(path)?;
+        let size = metadata.len();
+        if let Some(max) = max_hash_bytes {
+            if size > max {
+                return Ok(None);
+            }
+        }
+
+        let mtime = metadata.modified()?;
+        let key = (path.to_path_buf(), size, mtime);
+        if let Some(cached) = self.digest_cache.lock().unwrap().get(&key) {
+            return Ok(Some(cached.clone()));
+        }
+
+        let mut hasher = blake3::Hasher::new();
+        let mut file = std::fs::File::open(path)?;
+        let mut buffer = [0u8; HASH_CHUNK_SIZE];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+        let digest = format!("b3:{}", hasher.finalize().to_hex());
+
+        self.digest_cache.lock().unwrap().insert(key, digest.clone());
+        Ok(Some(digest))
+    }
+
+    /// Whether a *symlink-resolved* path still lives under one of the allowed roots. Unlike
+    /// `is_path_allowed`, this re-resolves each allowed root too, so a symlink inside an allowed
+    /// directory that points outside it is correctly rejected rather than silently trusted.
+    pub fn is_canonical_path_allowed(&self, real_path: &Path) -> bool {
+        self.allowed_directories.iter().any(|allowed| {
+            let allowed_real = std::fs::canonicalize(allowed).unwrap_or_else(|_| allowed.clone());
+            real_path.starts_with(&allowed_real)
+        })
+    }
+
+    /// Lexical allowed-directory check plus a symlink-escape check: walks up from `path` to the
+    /// nearest ancestor that actually exists on disk, resolves *that*, and verifies the resolved
+    /// path is still under an allowed root. Closes the same hole `is_canonical_path_allowed`
+    /// closes for reads — a symlink sitting inside an allowed directory can't be used to write
+    /// outside it, even though the path being created doesn't exist yet.
+    fn validate_write_target(&self, path: &Path) -> ServiceResult<PathBuf> {
+        let path = self.validate_path(path)?;
+
+        let mut ancestor = path.as_path();
+        loop {
+            match std::fs::canonicalize(ancestor) {
+                Ok(real) => {
+                    if !self.is_canonical_path_allowed(&real) {
+                        return Err(ServiceError::AccessDenied(format!(
+                            "{} resolves outside the allowed directories",
+                            path.display()
+                        )));
+                    }
+                    return Ok(path);
+                }
+                Err(_) => match ancestor.parent() {
+                    Some(parent) => ancestor = parent,
+                    None => return Ok(path),
+                },
+            }
+        }
+    }
+
+    pub fn create_directory(&self, path: &Path) -> ServiceResult<()> {
+        let path = self.validate_write_target(path)?;
+        std::fs::create_dir_all(path)?;
+        Ok(())
+    }
+
+    pub fn write_file(&self, path: &Path, contents: &str) -> ServiceResult<()> {
+        let path = self.validate_write_target(path)?;
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+
+    /// Rejects names that can never be valid entries and NFC-folds the rest, so every tool that
+    /// emits or consumes an entry name enforces the same invariants.
+    pub fn validate_entry_name(name: &str) -> Result<String, String> {
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(format!("invalid entry name: {name:?}"));
+        }
+        if name.contains('/') || name.contains('\\') || name.contains('\0') {
+            return Err(format!(
+                "entry name contains a path separator or NUL: {name:?}"
+            ));
+        }
+        Ok(name.nfc().collect())
+    }
+
+    /// Names that collide with another sibling once both are NFC-folded — a common source of
+    /// cross-platform breakage, since the filesystem may treat them as distinct.
+    pub fn find_normalization_collisions(names: &[String]) -> HashSet<String> {
+        let mut by_normalized: HashMap<String, Vec<&String>> = HashMap::new();
+        for name in names {
+            by_normalized
+                .entry(name.nfc().collect())
+                .or_default()
+                .push(name);
+        }
+        by_normalized
+            .into_values()
+            .filter(|originals| originals.len() > 1)
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// Collapses redundant separators/`.` components in a path before it's emitted to a caller.
+    pub fn normalize_path_string(path: &Path) -> String {
+        let mut normalized = PathBuf::new();
+        for component in path.components() {
+            normalized.push(component.as_os_str());
+        }
+        normalized.to_string_lossy().into_owned()
+    }
+
+    /// Combines a directory's children into one digest for the directory itself, folding in each
+    /// child's name alongside its digest so identical subtrees hash identically regardless of
+    /// where they live on disk, while two directories whose files merely share contents under
+    /// different names do *not* hash identically.
+    pub fn combine_digests(children: &[(String, String)]) -> String {
+        let mut sorted = children.to_vec();
+        sorted.sort();
+        let mut hasher = blake3::Hasher::new();
+        for (name, digest) in &sorted {
+            hasher.update(name.as_bytes());
+            hasher.update(b"\0");
+            hasher.update(digest.as_bytes());
+        }
+        format!("b3:{}", hasher.finalize().to_hex())
+    }
+}
+
+#[cfg(test)]
+mod combine_digests_tests {
+    use super::*;
+
+    #[test]
+    fn distinguishes_same_contents_under_different_names() {
+        let a = vec![("a.txt".to_string(), "b3:deadbeef".to_string())];
+        let b = vec![("b.txt".to_string(), "b3:deadbeef".to_string())];
+        assert_ne!(FileSystemService::combine_digests(&a), FileSystemService::combine_digests(&b));
+    }
+
+    #[test]
+    fn is_order_independent() {
+        let a = vec![
+            ("a.txt".to_string(), "b3:1".to_string()),
+            ("b.txt".to_string(), "b3:2".to_string()),
+        ];
+        let b = vec![
+            ("b.txt".to_string(), "b3:2".to_string()),
+            ("a.txt".to_string(), "b3:1".to_string()),
+        ];
+        assert_eq!(FileSystemService::combine_digests(&a), FileSystemService::combine_digests(&b));
+    }
+}
+
+#[cfg(all(test, unix))]
+mod symlink_jail_tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    fn unique_dir(label: &str) -> PathBuf {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!(
+            "aichemist-fs-service-test-{label}-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn walk_files_skips_symlink_escaping_allowed_root() {
+        let allowed = unique_dir("walk-files-allowed");
+        let outside = unique_dir("walk-files-outside");
+        std::fs::write(outside.join("secret.txt"), b"secret").unwrap();
+        symlink(&outside, allowed.join("escape")).unwrap();
+        std::fs::write(allowed.join("inside.txt"), b"inside").unwrap();
+
+        let service = FileSystemService::new(vec![allowed.clone()]);
+        let files = service.walk_files(&allowed).await.unwrap();
+
+        assert!(files.iter().all(|f| !f.starts_with(&outside)));
+        assert!(files.iter().all(|f| !f.starts_with(allowed.join("escape"))));
+        assert!(files.iter().any(|f| f.ends_with("inside.txt")));
+
+        let _ = std::fs::remove_dir_all(&allowed);
+        let _ = std::fs::remove_dir_all(&outside);
+    }
+}
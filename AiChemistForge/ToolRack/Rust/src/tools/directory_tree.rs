@@ -1,16 +1,26 @@
-use std::path::Path;
+use std::collections::HashSet;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
 
 use rust_mcp_schema::{schema_utils::CallToolError, CallToolResult};
 use rust_mcp_sdk::macros::{mcp_tool, JsonSchema};
 use serde_json::json;
 
+use crate::error::ServiceResult;
 use crate::fs_service::FileSystemService;
 
 #[mcp_tool(
     name = "directory_tree",
     description = concat!("Generates a recursive tree view of a directory's contents as a JSON formatted string. ",
-    "Each item in the tree includes 'name' and 'type' ('file' or 'directory'). ",
+    "Each item in the tree includes 'name' and 'type' ('file' or 'directory'), with directories carrying a nested 'children' array. ",
     "This provides a structured overview of a directory, useful for exploration and context gathering. ",
+    "Use 'max_depth' to cap how many levels deep the walk recurses, and 'ignore' to skip entries whose path contains any of the given prefixes/patterns. ",
+    "Set 'include_metadata' to attach size, timestamps, and (on Unix) dev/inode/nlink/mode to every node. ",
+    "Set 'compute_digest' to attach a BLAKE3 'digest' (\"b3:<hex>\") to every file node, with directory nodes hashing the sorted (name, digest) pairs of their children so two directories whose files merely share contents under different names don't collide; 'max_hash_bytes' skips hashing files above that size. ",
+    "Entry names are validated and NFC-normalized; problems (reserved names, separators, NUL, or a collision with a sibling after normalization) are reported as a 'name_warnings' array on the affected node rather than failing the call. ",
+    "Every node's resolved target is checked against the allowed directories regardless of 'canonical': a node whose target escapes every allowed directory is marked 'escaped': true and is not traversed, so a symlink can never be used to read outside the allowed roots. Set 'canonical' to additionally follow symlinks and emit each node's fully resolved absolute path in 'name'. ",
+    "When the server is configured with a persistent tree index, a subtree whose directory mtime hasn't changed since it was last scanned is served from the index instead of being walked again; set 'refresh' to force a full rescan. ",
     "IMPORTANT: The path provided MUST be an absolute path (e.g., D:\\data\\folder or /srv/project_files). Relative paths are not supported. ",
     "The operation is restricted to pre-configured allowed directories on the server."),
     destructive_hint = false,
@@ -22,28 +32,321 @@ use crate::fs_service::FileSystemService;
 pub struct DirectoryTreeTool {
     /// The **absolute root path** for which to generate the directory tree (e.g., `D:\\data\\folder` or `/srv/project_files`).
     pub path: String,
+    /// Maximum number of levels to recurse below the root. `None` walks the whole subtree.
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    /// Path prefixes/substrings to exclude; any entry whose path contains one of these is skipped.
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    /// When `true`, attach filesystem metadata (size, timestamps, symlink flag, Unix stat bits) to each node.
+    #[serde(default)]
+    pub include_metadata: bool,
+    /// When `true`, attach a BLAKE3 `digest` to every node (files hash their contents, directories
+    /// hash their children's sorted `(name, digest)` pairs so same-content-different-name
+    /// children don't collide).
+    #[serde(default)]
+    pub compute_digest: bool,
+    /// Files larger than this are left undigested (their node gets `"digest_skipped": true` instead).
+    #[serde(default)]
+    pub max_hash_bytes: Option<u64>,
+    /// When `true`, follow symlinks and emit each node's fully resolved canonical absolute path
+    /// in `name` instead of a bare name. The symlink-escape jail itself (a node whose resolved
+    /// target escapes every allowed root is marked `"escaped": true` and not traversed) always
+    /// runs, regardless of this flag.
+    #[serde(default)]
+    pub canonical: bool,
+    /// When `true`, bypass the persistent tree index (if configured) and force a full rescan.
+    #[serde(default)]
+    pub refresh: bool,
 }
+
+/// Per-walk settings threaded through the recursive node builder, kept separate from the tool
+/// params so the recursion signature doesn't grow with every new option.
+struct WalkOptions<'a> {
+    max_depth: Option<usize>,
+    ignore: &'a [String],
+    include_metadata: bool,
+    compute_digest: bool,
+    max_hash_bytes: Option<u64>,
+    canonical: bool,
+    refresh: bool,
+    /// Hash of every option above that affects a node's shape except `max_depth`, whose remaining
+    /// budget is folded in per node by `cache_key_fingerprint`, so the tree index never serves a
+    /// cached node computed under a different set of options.
+    fingerprint: u64,
+}
+
+impl<'a> WalkOptions<'a> {
+    fn new(params: &'a DirectoryTreeTool) -> Self {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        params.ignore.hash(&mut hasher);
+        params.include_metadata.hash(&mut hasher);
+        params.compute_digest.hash(&mut hasher);
+        params.max_hash_bytes.hash(&mut hasher);
+        params.canonical.hash(&mut hasher);
+
+        Self {
+            max_depth: params.max_depth,
+            ignore: &params.ignore,
+            include_metadata: params.include_metadata,
+            compute_digest: params.compute_digest,
+            max_hash_bytes: params.max_hash_bytes,
+            canonical: params.canonical,
+            refresh: params.refresh,
+            fingerprint: hasher.finish(),
+        }
+    }
+
+    /// `fingerprint` identifies a call's options other than `max_depth`, which is a budget measured
+    /// from the scan root: the same directory reached at different depths across two calls (even
+    /// with identical options) yields differently-shaped nodes. Folding the *remaining* depth
+    /// budget at this node into the cache key means two nodes only ever share a key when they'd
+    /// produce the same shape — whether they came from the same call or two unrelated ones.
+    fn cache_key_fingerprint(&self, depth: usize) -> u64 {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        self.fingerprint.hash(&mut hasher);
+        self.max_depth.map(|max| max.saturating_sub(depth)).hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+#[cfg(test)]
+mod cache_key_tests {
+    use super::*;
+
+    fn params(max_depth: Option<usize>) -> DirectoryTreeTool {
+        DirectoryTreeTool {
+            path: "/allowed".to_string(),
+            max_depth,
+            ignore: Vec::new(),
+            include_metadata: false,
+            compute_digest: false,
+            max_hash_bytes: None,
+            canonical: false,
+            refresh: false,
+        }
+    }
+
+    #[test]
+    fn same_remaining_depth_yields_same_cache_key() {
+        // A scan of `/project` with max_depth: 2 reaches `/project/src` at depth 1 (1 level of
+        // children remaining); a direct scan of `/project/src` with max_depth: 1 is at depth 0
+        // with the same 1 level remaining. Both should be able to share a cached node.
+        let nested_scan = WalkOptions::new(&params(Some(2)));
+        let direct_scan = WalkOptions::new(&params(Some(1)));
+        assert_eq!(
+            nested_scan.cache_key_fingerprint(1),
+            direct_scan.cache_key_fingerprint(0)
+        );
+    }
+
+    #[test]
+    fn different_remaining_depth_yields_different_cache_key() {
+        // The same options, but reached at different depths within the same call, must never
+        // collide: the node at depth 0 has strictly more children materialized than at depth 1.
+        let options = WalkOptions::new(&params(Some(2)));
+        assert_ne!(
+            options.cache_key_fingerprint(0),
+            options.cache_key_fingerprint(1)
+        );
+    }
+}
+
 impl DirectoryTreeTool {
     pub async fn run_tool(
         params: Self,
         context: &FileSystemService,
     ) -> std::result::Result<CallToolResult, CallToolError> {
-        let entries = context
-            .list_directory(Path::new(&params.path))
+        let root = Path::new(&params.path);
+        let mut visited = HashSet::new();
+        let options = WalkOptions::new(&params);
+
+        let tree = Self::build_node(root, context, 0, &options, &mut visited)
             .await
             .map_err(CallToolError::new)?;
 
-        let json_tree: Vec<serde_json::Value> = entries
-            .iter()
-            .map(|entry| {
-                json!({
-                    "name": entry.file_name().to_str().unwrap_or_default(),
-                    "type": if entry.path().is_dir(){"directory"}else{"file"}
-                })
-            })
-            .collect();
-        let json_str =
-            serde_json::to_string_pretty(&json!(json_tree)).map_err(CallToolError::new)?;
+        let json_str = serde_json::to_string_pretty(&tree).map_err(CallToolError::new)?;
         Ok(CallToolResult::text_content(json_str, None))
     }
+
+    fn push_name_warning(node: &mut serde_json::Value, warning: String) {
+        let Some(obj) = node.as_object_mut() else {
+            return;
+        };
+        match obj.get_mut("name_warnings").and_then(|w| w.as_array_mut()) {
+            Some(warnings) => warnings.push(json!(warning)),
+            None => {
+                obj.insert("name_warnings".into(), json!([warning]));
+            }
+        }
+    }
+
+    fn is_ignored(path: &Path, ignore: &[String]) -> bool {
+        if ignore.is_empty() {
+            return false;
+        }
+        let path_str = path.to_string_lossy();
+        ignore.iter().any(|pattern| path_str.contains(pattern.as_str()))
+    }
+
+    /// Depth-first walk building one JSON node per entry. Boxed because an `async fn` cannot
+    /// recurse into itself directly.
+    fn build_node<'a>(
+        path: &'a Path,
+        context: &'a FileSystemService,
+        depth: usize,
+        options: &'a WalkOptions<'a>,
+        visited: &'a mut HashSet<PathBuf>,
+    ) -> Pin<Box<dyn Future<Output = ServiceResult<serde_json::Value>> + 'a>> {
+        Box::pin(async move {
+            context.validate_path(path)?;
+
+            let name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default()
+                .to_string();
+            let is_dir = path.is_dir();
+
+            let mut node = serde_json::Map::new();
+            node.insert("name".into(), json!(name));
+            node.insert(
+                "type".into(),
+                json!(if is_dir { "directory" } else { "file" }),
+            );
+
+            let mut name_warnings = Vec::new();
+            match FileSystemService::validate_entry_name(&name) {
+                Ok(normalized) if normalized != name => {
+                    node.insert("normalized_name".into(), json!(normalized));
+                }
+                Ok(_) => {}
+                Err(warning) => name_warnings.push(warning),
+            }
+            if !name_warnings.is_empty() {
+                node.insert("name_warnings".into(), json!(name_warnings));
+            }
+
+            if options.include_metadata {
+                match context.entry_metadata(path) {
+                    Ok(metadata) => {
+                        node.insert("metadata".into(), metadata);
+                    }
+                    Err(err) => {
+                        node.insert("metadata_error".into(), json!(err.to_string()));
+                    }
+                }
+            }
+
+            // The symlink-escape jail runs unconditionally on every node, not just when
+            // `canonical` is requested: otherwise a symlink inside an allowed directory that
+            // points outside it is silently followed by `path.is_dir()`/`list_directory()`
+            // below, and its contents leak under a call that never opted into canonical mode.
+            let real_path = std::fs::canonicalize(path).ok();
+            let escaped = real_path
+                .as_ref()
+                .map_or(true, |real| !context.is_canonical_path_allowed(real));
+
+            if options.canonical {
+                if let Some(real) = &real_path {
+                    node.insert(
+                        "name".into(),
+                        json!(FileSystemService::normalize_path_string(real)),
+                    );
+                }
+            }
+
+            if escaped {
+                node.insert("escaped".into(), json!(true));
+                if is_dir {
+                    node.insert("children".into(), json!([]));
+                }
+                return Ok(serde_json::Value::Object(node));
+            }
+
+            if !is_dir {
+                if options.compute_digest {
+                    match context.file_digest(path, options.max_hash_bytes) {
+                        Ok(Some(digest)) => {
+                            node.insert("digest".into(), json!(digest));
+                        }
+                        Ok(None) => {
+                            node.insert("digest_skipped".into(), json!(true));
+                        }
+                        Err(err) => {
+                            node.insert("digest_error".into(), json!(err.to_string()));
+                        }
+                    }
+                }
+                return Ok(serde_json::Value::Object(node));
+            }
+
+            // Guard against symlink cycles: once a canonical directory path has been
+            // visited, stop descending into it again.
+            let canonical = real_path.clone().unwrap_or_else(|| path.to_path_buf());
+            if !visited.insert(canonical) {
+                node.insert("children".into(), json!([]));
+                return Ok(serde_json::Value::Object(node));
+            }
+
+            let dir_mtime = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+            let cache_fingerprint = options.cache_key_fingerprint(depth);
+            if !options.refresh {
+                if let (Some(index), Some(mtime)) = (context.index(), dir_mtime) {
+                    if let Some(cached) = index.get(path, mtime, cache_fingerprint) {
+                        return Ok(cached);
+                    }
+                }
+            }
+
+            let mut children = Vec::new();
+            let mut child_digest_entries: Vec<(String, String)> = Vec::new();
+            if options.max_depth.map_or(true, |max| depth < max) {
+                let entries = context.list_directory(path).await?;
+                let sibling_names: Vec<String> = entries
+                    .iter()
+                    .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                    .collect();
+                let collisions = FileSystemService::find_normalization_collisions(&sibling_names);
+
+                for entry in entries {
+                    let child_path = entry.path();
+                    if Self::is_ignored(&child_path, options.ignore) {
+                        continue;
+                    }
+                    let mut child = Self::build_node(&child_path, context, depth + 1, options, visited).await?;
+                    let child_name = entry.file_name().to_string_lossy().into_owned();
+                    if collisions.contains(&child_name) {
+                        Self::push_name_warning(
+                            &mut child,
+                            format!("name collides with a sibling after NFC normalization: {child_name}"),
+                        );
+                    }
+                    if options.compute_digest {
+                        if let Some(digest) = child.get("digest").and_then(|d| d.as_str()) {
+                            child_digest_entries.push((child_name, digest.to_string()));
+                        }
+                    }
+                    children.push(child);
+                }
+            }
+
+            if options.compute_digest {
+                node.insert(
+                    "digest".into(),
+                    json!(FileSystemService::combine_digests(&child_digest_entries)),
+                );
+            }
+            node.insert("children".into(), json!(children));
+
+            let node = serde_json::Value::Object(node);
+            if let (Some(index), Some(mtime)) = (context.index(), dir_mtime) {
+                let _ = index.put(path, mtime, cache_fingerprint, &node);
+            }
+
+            Ok(node)
+        })
+    }
 }
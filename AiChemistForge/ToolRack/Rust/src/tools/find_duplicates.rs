@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use rust_mcp_schema::{schema_utils::CallToolError, CallToolResult};
+use rust_mcp_sdk::macros::{mcp_tool, JsonSchema};
+use serde_json::json;
+
+use crate::fs_service::FileSystemService;
+
+#[mcp_tool(
+    name = "find_duplicates",
+    description = concat!("Walks an allowed directory and groups files by their BLAKE3 content digest, ",
+    "returning clusters of byte-identical paths. ",
+    "Use 'max_hash_bytes' to skip hashing (and therefore comparing) files above a given size. ",
+    "IMPORTANT: The path provided MUST be an absolute path. Relative paths are not supported. ",
+    "The operation is restricted to pre-configured allowed directories on the server."),
+    destructive_hint = false,
+    idempotent_hint = false,
+    open_world_hint = false,
+    read_only_hint = true
+)]
+#[derive(::serde::Deserialize, ::serde::Serialize, Clone, Debug, JsonSchema)]
+pub struct FindDuplicatesTool {
+    /// The **absolute root path** to scan for duplicate files.
+    pub path: String,
+    /// Files larger than this are skipped rather than hashed.
+    #[serde(default)]
+    pub max_hash_bytes: Option<u64>,
+}
+
+impl FindDuplicatesTool {
+    pub async fn run_tool(
+        params: Self,
+        context: &FileSystemService,
+    ) -> std::result::Result<CallToolResult, CallToolError> {
+        let root = Path::new(&params.path);
+        let files = context.walk_files(root).await.map_err(CallToolError::new)?;
+
+        let mut by_digest: HashMap<String, Vec<String>> = HashMap::new();
+        for file in files {
+            let Some(digest) = context
+                .file_digest(&file, params.max_hash_bytes)
+                .map_err(CallToolError::new)?
+            else {
+                continue;
+            };
+            by_digest
+                .entry(digest)
+                .or_default()
+                .push(FileSystemService::normalize_path_string(&file));
+        }
+
+        let clusters: Vec<serde_json::Value> = by_digest
+            .into_iter()
+            .filter(|(_, paths)| paths.len() > 1)
+            .map(|(digest, paths)| json!({ "digest": digest, "paths": paths }))
+            .collect();
+
+        let json_str = serde_json::to_string_pretty(&clusters).map_err(CallToolError::new)?;
+        Ok(CallToolResult::text_content(json_str, None))
+    }
+}
@@ -0,0 +1,101 @@
+use std::path::Path;
+
+use rust_mcp_schema::{schema_utils::CallToolError, CallToolResult};
+use rust_mcp_sdk::macros::{mcp_tool, JsonSchema};
+use serde_json::json;
+
+use crate::error::{ServiceError, ServiceResult};
+use crate::fs_service::FileSystemService;
+
+#[mcp_tool(
+    name = "write_tree",
+    description = concat!("Materializes a directory layout from a JSON spec under an absolute root 'path'. ",
+    "'tree' is a JSON object where each key is a child name: an object value creates a directory and recurses, ",
+    "a string value creates a file with that content, and a null value creates an empty directory. ",
+    "Child names may not contain a path separator or '..', so the spec cannot escape the target root. ",
+    "Two sibling keys that are distinct but collide once NFC-normalized (e.g. precomposed vs. decomposed accents) would silently overwrite the same on-disk path on a normalizing filesystem; such a spec is rejected with an error. The check runs per object level as that level is reached, so paths already created for enclosing levels and earlier siblings remain on disk. ",
+    "A symlink sitting inside an allowed directory cannot be used to write outside it: the nearest existing ancestor of every path is resolved and checked against the allowed directories before anything is written. ",
+    "IMPORTANT: The path provided MUST be an absolute path. Relative paths are not supported. ",
+    "The operation is restricted to pre-configured allowed directories on the server."),
+    destructive_hint = true,
+    idempotent_hint = false,
+    open_world_hint = false,
+    read_only_hint = false
+)]
+#[derive(::serde::Deserialize, ::serde::Serialize, Clone, Debug, JsonSchema)]
+pub struct WriteTreeTool {
+    /// The **absolute root path** under which the tree is created. Created, with any missing
+    /// parents, if it does not exist; the root itself is not listed in the returned `created` paths.
+    pub path: String,
+    /// The directory layout to materialize under `path`.
+    pub tree: serde_json::Value,
+}
+
+impl WriteTreeTool {
+    pub async fn run_tool(
+        params: Self,
+        context: &FileSystemService,
+    ) -> std::result::Result<CallToolResult, CallToolError> {
+        let root = Path::new(&params.path);
+        let spec = params
+            .tree
+            .as_object()
+            .ok_or_else(|| {
+                CallToolError::new(ServiceError::InvalidPath(
+                    "tree must be a JSON object".to_string(),
+                ))
+            })?;
+
+        let mut created = Vec::new();
+        Self::write_spec(root, spec, context, &mut created).map_err(CallToolError::new)?;
+
+        let json_str = serde_json::to_string_pretty(&json!({ "created": created }))
+            .map_err(CallToolError::new)?;
+        Ok(CallToolResult::text_content(json_str, None))
+    }
+
+    fn write_spec(
+        base: &Path,
+        spec: &serde_json::Map<String, serde_json::Value>,
+        context: &FileSystemService,
+        created: &mut Vec<String>,
+    ) -> ServiceResult<()> {
+        context.create_directory(base)?;
+
+        let sibling_names: Vec<String> = spec.keys().cloned().collect();
+        let collisions = FileSystemService::find_normalization_collisions(&sibling_names);
+        if let Some(name) = collisions.iter().next() {
+            return Err(ServiceError::InvalidPath(format!(
+                "sibling keys collide after NFC normalization under {}: {name:?}",
+                base.display()
+            )));
+        }
+
+        for (name, value) in spec {
+            FileSystemService::validate_entry_name(name).map_err(ServiceError::InvalidPath)?;
+            let child_path = base.join(name);
+
+            match value {
+                serde_json::Value::Object(children) => {
+                    created.push(FileSystemService::normalize_path_string(&child_path));
+                    Self::write_spec(&child_path, children, context, created)?;
+                }
+                serde_json::Value::String(content) => {
+                    context.write_file(&child_path, content)?;
+                    created.push(FileSystemService::normalize_path_string(&child_path));
+                }
+                serde_json::Value::Null => {
+                    context.create_directory(&child_path)?;
+                    created.push(FileSystemService::normalize_path_string(&child_path));
+                }
+                _ => {
+                    return Err(ServiceError::InvalidPath(format!(
+                        "unsupported tree value for {name}: expected object, string, or null"
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
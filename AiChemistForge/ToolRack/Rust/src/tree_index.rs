@@ -0,0 +1,70 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ServiceError, ServiceResult};
+
+#[derive(Serialize, Deserialize)]
+struct IndexedEntry {
+    node: serde_json::Value,
+    mtime_nanos: u128,
+}
+
+/// On-disk cache of previously computed `directory_tree` nodes, keyed by absolute path *and* a
+/// fingerprint of the request options that produced the cached node (see
+/// `WalkOptions::cache_key_fingerprint`), which also folds in the remaining depth budget at that
+/// node. Folding the fingerprint into the key means a scan with different options (e.g.
+/// `include_metadata` toggled, a different `max_depth`) — or the same directory reached at a
+/// different depth across two separate scans — can never be served another scan's cached shape.
+///
+/// Backed by `sled` so repeat scans of a mostly-static tree can serve a cached subtree
+/// instead of re-walking it, as long as the directory's mtime hasn't changed since it was
+/// cached.
+pub struct TreeIndex {
+    db: sled::Db,
+}
+
+impl TreeIndex {
+    pub fn open(path: &Path) -> ServiceResult<Self> {
+        let db = sled::open(path).map_err(|err| ServiceError::Index(err.to_string()))?;
+        Ok(Self { db })
+    }
+
+    /// Returns the cached node for `key_path` under `options_fingerprint` if present and its
+    /// recorded mtime still matches.
+    pub fn get(&self, key_path: &Path, mtime: SystemTime, options_fingerprint: u64) -> Option<serde_json::Value> {
+        let bytes = self
+            .db
+            .get(Self::key(key_path, options_fingerprint))
+            .ok()??;
+        let entry: IndexedEntry = serde_json::from_slice(&bytes).ok()?;
+        (entry.mtime_nanos == Self::mtime_nanos(mtime)).then_some(entry.node)
+    }
+
+    pub fn put(
+        &self,
+        key_path: &Path,
+        mtime: SystemTime,
+        options_fingerprint: u64,
+        node: &serde_json::Value,
+    ) -> ServiceResult<()> {
+        let entry = IndexedEntry {
+            node: node.clone(),
+            mtime_nanos: Self::mtime_nanos(mtime),
+        };
+        let bytes = serde_json::to_vec(&entry).map_err(|err| ServiceError::Index(err.to_string()))?;
+        self.db
+            .insert(Self::key(key_path, options_fingerprint), bytes)
+            .map_err(|err| ServiceError::Index(err.to_string()))?;
+        Ok(())
+    }
+
+    fn key(key_path: &Path, options_fingerprint: u64) -> Vec<u8> {
+        format!("{}\0{:016x}", key_path.display(), options_fingerprint).into_bytes()
+    }
+
+    fn mtime_nanos(time: SystemTime) -> u128 {
+        time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos()
+    }
+}